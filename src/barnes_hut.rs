@@ -1,49 +1,153 @@
-use bevy::{math::vec3, prelude::Vec3};
+use bevy::{
+    math::vec3,
+    prelude::{Mat3, Vec3},
+};
 
 pub trait Id: Copy + Eq {}
 impl<T> Id for T where T: Copy + Eq {}
 
+/// One force term active only over the distance band `[min_dist, max_dist)`,
+/// so a kind pair can express e.g. short-range repulsion and long-range
+/// attraction as separate bands instead of a single force/exponent pair.
+#[derive(Debug, Clone, Copy)]
+pub struct ForceBand {
+    pub force: f32,
+    pub distance_exp: i32,
+    pub min_dist: f32,
+    pub max_dist: f32,
+}
+
+impl ForceBand {
+    fn contains(&self, d: f32) -> bool {
+        d >= self.min_dist && d < self.max_dist
+    }
+}
+
+/// How the bands active at a given distance combine into one coefficient.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CombineMode {
+    /// Add every active band's contribution.
+    Sum,
+    /// Mean of every active band's contribution.
+    Average,
+    /// Only the first (in list order) active band contributes.
+    Priority,
+}
+
+/// An ordered set of [`ForceBand`]s for one kind pair, combined per `mode`.
+#[derive(Debug, Clone)]
+pub struct ForceRules {
+    pub bands: Vec<ForceBand>,
+    pub mode: CombineMode,
+}
+
+/// Weights and traversal parameters for one kind's boids-style flocking,
+/// bundled together the way [`ForceRules`] bundles a kind pair's force bands
+/// instead of threading each one through `flock` as its own argument.
+#[derive(Debug, Clone, Copy)]
+pub struct FlockRules {
+    pub radius: f32,
+    pub cohesion: f32,
+    pub alignment: f32,
+    pub separation: f32,
+    pub theta: f32,
+}
+
 #[derive(Debug)]
 pub struct BarnesHutTree<TId: Id> {
     root: Node<TId>,
     count: usize,
+    // Side length of the periodic domain bodies wrap around.
+    domain_size: f32,
 }
 
 impl<TId: Id> BarnesHutTree<TId> {
-    pub fn new(min_bound: Vec3, max_bound: Vec3) -> Self {
+    /// Builds a tree over a cubic domain of side `domain_size` centered at
+    /// the origin, whose bodies wrap around instead of drifting off to
+    /// infinity. `force` then uses the minimum-image displacement (and the
+    /// 27 nearest periodic images) instead of the raw one.
+    pub fn new(domain_size: f32) -> Self {
         Self {
-            root: Node::new(
-                (max_bound + min_bound) / 2.0,
-                (max_bound - min_bound).max_element(),
-            ),
+            root: Node::new(Vec3::ZERO, domain_size),
             count: 0,
+            domain_size,
         }
     }
 
-    pub fn insert(&mut self, id: TId, position: Vec3, mass: f32) {
-        self.root.insert(id, position, mass);
+    pub fn insert(&mut self, id: TId, position: Vec3, velocity: Vec3, mass: f32) {
+        self.root.insert(id, position, velocity, mass);
         self.count += 1;
     }
 
-    pub fn force(
-        &self,
+    /// Removes a previously inserted body, pruning and re-collapsing the
+    /// affected branch so the tree stays minimal without a full rebuild.
+    pub fn remove(&mut self, id: TId, position: Vec3) {
+        if self.root.remove(id, position).is_some() {
+            self.count -= 1;
+        }
+    }
+
+    /// Moves a body from `old_position`/`old_velocity` to `new_position`/
+    /// `new_velocity` in place. Cheaper than a `remove` + `insert` pair when
+    /// the body stays in the same branch, since only the moments along the
+    /// path need adjusting.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
         id: TId,
-        position: Vec3,
-        force_constant: f32,
-        distance_exp: i32,
-        theta: f32,
-    ) -> Vec3 {
+        old_position: Vec3,
+        new_position: Vec3,
+        old_velocity: Vec3,
+        new_velocity: Vec3,
+        mass: f32,
+    ) {
+        self.root.update(
+            id,
+            old_position,
+            new_position,
+            old_velocity,
+            new_velocity,
+            mass,
+        );
+    }
+
+    pub fn force(&self, id: TId, position: Vec3, rules: &ForceRules, theta: f32) -> Vec3 {
         self.root
-            .force(id, position, force_constant, distance_exp, theta)
+            .force(id, position, rules, theta, self.domain_size)
+    }
+
+    /// Boids-style steering: cohesion toward a neighborhood's center of
+    /// mass, alignment toward its average velocity, and short-range
+    /// separation, each term weighted by the neighboring body's or accepted
+    /// node's mass the same way gravity is.
+    pub fn flock(&self, id: TId, position: Vec3, velocity: Vec3, rules: &FlockRules) -> Vec3 {
+        self.root
+            .flock(id, position, velocity, rules, self.domain_size)
     }
 }
 
 #[derive(Debug)]
 struct Node<TId: Id> {
+    // Raw zeroth/first moments (total mass and mass-weighted position sum)
+    // rather than a running-average center of mass, since that's what lets
+    // `remove` subtract a body back out without drifting numerically.
     mass: f32,
-    center_of_mass: Vec3,
+    moment: Vec3,
+    // Raw second moment `Σ m_i (r_i ⊗ r_i)`, accumulated the same way as
+    // `moment` so the traceless quadrupole tensor can be derived on demand
+    // (see `quadrupole`) without drifting under `remove`/`update`.
+    second_moment: Mat3,
+    // Mass-weighted velocity sum, mirroring `moment`, so a cell can expose
+    // its average velocity for the flocking traversal without storing every
+    // member's velocity.
+    velocity_moment: Vec3,
     midpoint: Vec3,
     size: f32,
+    // Number of bodies in this node's subtree, used on removal to prune an
+    // emptied branch and to know when a `Node` has collapsed down to a
+    // single body and can revert to a `Leaf`. The branch itself is found
+    // from the body's position.
+    count: usize,
     kind: NodeKind<TId>,
 }
 
@@ -51,14 +155,51 @@ impl<TId: Id> Node<TId> {
     fn new(midpoint: Vec3, size: f32) -> Self {
         Self {
             mass: 0.0,
-            center_of_mass: Vec3::ZERO,
+            moment: Vec3::ZERO,
+            second_moment: Mat3::ZERO,
+            velocity_moment: Vec3::ZERO,
             midpoint,
             size,
+            count: 0,
             kind: NodeKind::Empty,
         }
     }
 
-    fn insert(&mut self, id: TId, position: Vec3, mass: f32) {
+    fn center_of_mass(&self) -> Vec3 {
+        if self.mass > 0.0 {
+            self.moment / self.mass
+        } else {
+            Vec3::ZERO
+        }
+    }
+
+    fn average_velocity(&self) -> Vec3 {
+        if self.mass > 0.0 {
+            self.velocity_moment / self.mass
+        } else {
+            Vec3::ZERO
+        }
+    }
+
+    /// Traceless quadrupole tensor `Q_jk = Σ m_i (3 x_j x_k − |x|² δ_jk)`
+    /// with `x = r_i − center_of_mass`, derived from the raw second moment
+    /// via the parallel axis theorem: `Σ m_i (x_i⊗x_i) = S − M0·(R⊗R)`.
+    fn quadrupole(&self) -> Mat3 {
+        let r = self.center_of_mass();
+        let centered = self.second_moment - outer(r) * self.mass;
+        let trace = centered.x_axis.x + centered.y_axis.y + centered.z_axis.z;
+        centered * 3.0 - Mat3::from_diagonal(Vec3::splat(trace))
+    }
+
+    fn insert(&mut self, id: TId, position: Vec3, velocity: Vec3, mass: f32) {
+        // Computed up front, before `match &mut self.kind` below takes a
+        // mutable borrow of `self.kind`: both only read `self.moment`/
+        // `self.velocity_moment`/`self.mass`, but calling them as `&self`
+        // methods once `prev_id` is borrowed out of `self.kind` would
+        // conflict with that borrow.
+        let prev_position = self.center_of_mass();
+        let prev_velocity = self.average_velocity();
+
         match &mut self.kind {
             NodeKind::Empty => {
                 self.kind = NodeKind::Leaf(id);
@@ -87,62 +228,314 @@ impl<TId: Id> Node<TId> {
                     Node::new(min_midpoint + sub_size * vec3(1.0, 1.0, 1.0), sub_size),
                 ]);
 
-                nodes[branch_index(self.center_of_mass, self.midpoint)].insert(
+                nodes[branch_index(prev_position, self.midpoint)].insert(
                     *prev_id,
-                    self.center_of_mass,
+                    prev_position,
+                    prev_velocity,
                     self.mass,
                 );
 
-                nodes[branch_index(position, self.midpoint)].insert(id, position, mass);
+                nodes[branch_index(position, self.midpoint)].insert(id, position, velocity, mass);
 
                 self.kind = NodeKind::Node(nodes);
             }
-            NodeKind::Node(node) => {
-                node[branch_index(position, self.midpoint)].insert(id, position, mass);
+            NodeKind::Node(nodes) => {
+                nodes[branch_index(position, self.midpoint)].insert(id, position, velocity, mass);
             }
         }
-        self.center_of_mass =
-            (self.center_of_mass * self.mass + position * mass) / (self.mass + mass);
+        self.moment += position * mass;
+        self.second_moment += outer(position) * mass;
+        self.velocity_moment += velocity * mass;
         self.mass += mass;
+        self.count += 1;
+    }
+
+    /// Returns the removed body's mass and velocity moment if `id` was
+    /// found under `position`.
+    fn remove(&mut self, id: TId, position: Vec3) -> Option<(f32, Vec3)> {
+        let (removed_mass, removed_velocity_moment) = match &mut self.kind {
+            NodeKind::Empty => None,
+            NodeKind::Leaf(leaf_id) => {
+                if *leaf_id == id {
+                    let removed = (self.mass, self.velocity_moment);
+                    self.kind = NodeKind::Empty;
+                    Some(removed)
+                } else {
+                    None
+                }
+            }
+            NodeKind::Node(nodes) => {
+                let idx = branch_index(position, self.midpoint);
+                nodes[idx].remove(id, position)
+            }
+        }?;
+
+        self.moment -= position * removed_mass;
+        self.second_moment -= outer(position) * removed_mass;
+        self.velocity_moment -= removed_velocity_moment;
+        self.mass -= removed_mass;
+        self.count -= 1;
+
+        if let NodeKind::Node(nodes) = &self.kind {
+            if self.count == 0 {
+                self.kind = NodeKind::Empty;
+            } else if self.count == 1 {
+                let remaining_id = nodes
+                    .iter()
+                    .find_map(Node::single_id)
+                    .expect("a node with one remaining body must still contain its leaf");
+                self.kind = NodeKind::Leaf(remaining_id);
+            }
+        }
+
+        Some((removed_mass, removed_velocity_moment))
     }
 
+    /// Moves a body in place, only touching the path between its old and
+    /// new branch; falls back to a remove + insert once the paths diverge.
+    #[allow(clippy::too_many_arguments)]
+    fn update(
+        &mut self,
+        id: TId,
+        old_position: Vec3,
+        new_position: Vec3,
+        old_velocity: Vec3,
+        new_velocity: Vec3,
+        mass: f32,
+    ) {
+        match &mut self.kind {
+            NodeKind::Empty => {}
+            NodeKind::Leaf(leaf_id) if *leaf_id == id => {
+                self.mass = mass;
+                self.moment = mass * new_position;
+                self.second_moment = outer(new_position) * mass;
+                self.velocity_moment = mass * new_velocity;
+            }
+            NodeKind::Leaf(_) => panic!("update: id not found at old_position"),
+            NodeKind::Node(nodes) => {
+                let old_idx = branch_index(old_position, self.midpoint);
+                let new_idx = branch_index(new_position, self.midpoint);
+
+                if old_idx == new_idx {
+                    nodes[old_idx].update(
+                        id,
+                        old_position,
+                        new_position,
+                        old_velocity,
+                        new_velocity,
+                        mass,
+                    );
+                    self.moment += mass * (new_position - old_position);
+                    self.second_moment += (outer(new_position) - outer(old_position)) * mass;
+                    self.velocity_moment += mass * (new_velocity - old_velocity);
+                } else {
+                    let (removed_mass, removed_velocity_moment) = nodes[old_idx]
+                        .remove(id, old_position)
+                        .expect("update: id not found at old_position");
+                    self.mass -= removed_mass;
+                    self.moment -= removed_mass * old_position;
+                    self.second_moment -= outer(old_position) * removed_mass;
+                    self.velocity_moment -= removed_velocity_moment;
+                    self.count -= 1;
+
+                    nodes[new_idx].insert(id, new_position, new_velocity, mass);
+                    self.mass += mass;
+                    self.moment += mass * new_position;
+                    self.second_moment += outer(new_position) * mass;
+                    self.velocity_moment += mass * new_velocity;
+                    self.count += 1;
+                }
+            }
+        }
+    }
+
+    /// If this subtree holds exactly one body, returns its id.
+    fn single_id(&self) -> Option<TId> {
+        if self.count != 1 {
+            return None;
+        }
+        match &self.kind {
+            NodeKind::Empty => None,
+            NodeKind::Leaf(id) => Some(*id),
+            NodeKind::Node(nodes) => nodes.iter().find_map(Node::single_id),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn force(
         &self,
         id: TId,
         position: Vec3,
-        force_constant: f32,
-        distance_exp: i32,
+        rules: &ForceRules,
         theta: f32,
+        domain_size: f32,
+    ) -> Vec3 {
+        match &self.kind {
+            NodeKind::Empty => Vec3::ZERO,
+            NodeKind::Leaf(node_id) => {
+                if id != *node_id {
+                    self.point_force(position, rules, domain_size)
+                } else {
+                    Vec3::ZERO
+                }
+            }
+            NodeKind::Node(nodes) => {
+                let displacement = self.center_of_mass() - position;
+                let d = minimum_image(displacement, domain_size).length();
+                if self.size / d < theta {
+                    self.point_force(position, rules, domain_size)
+                } else {
+                    let mut force = Vec3::ZERO;
+                    for node in nodes.iter() {
+                        force += node.force(id, position, rules, theta, domain_size);
+                    }
+                    force
+                }
+            }
+        }
+    }
+
+    /// The field this node's monopole (plus quadrupole, when valid) exerts
+    /// at `position`. Under a periodic domain, sums the contribution of the
+    /// 27 nearest images of this node's center of mass rather than just the
+    /// nearest one, so bodies near the seam feel their wrapped-around
+    /// neighbors too.
+    fn point_force(&self, position: Vec3, rules: &ForceRules, domain_size: f32) -> Vec3 {
+        let center_of_mass = self.center_of_mass();
+        // `Q` depends only on this node's mass distribution, not on the
+        // image offset or active band, so it's computed once here instead
+        // of up to 27 * bands.len() times below.
+        let quadrupole = self.quadrupole();
+
+        let nearest = minimum_image(center_of_mass - position, domain_size);
+        let mut total = Vec3::ZERO;
+        for i in -1..=1 {
+            for j in -1..=1 {
+                for k in -1..=1 {
+                    let image_displacement =
+                        nearest + domain_size * vec3(i as f32, j as f32, k as f32);
+                    total +=
+                        self.rule_force(position, position + image_displacement, rules, quadrupole);
+                }
+            }
+        }
+        total
+    }
+
+    /// This node's contribution at `center_of_mass`, combining every band of
+    /// `rules` active at this distance per its [`CombineMode`]. Folds
+    /// straight over `rules.bands` rather than collecting the active ones
+    /// first, since this runs per node per periodic image in the hot path.
+    fn rule_force(
+        &self,
+        position: Vec3,
+        center_of_mass: Vec3,
+        rules: &ForceRules,
+        quadrupole: Mat3,
+    ) -> Vec3 {
+        let d = (center_of_mass - position).length();
+
+        if rules.mode == CombineMode::Priority {
+            return rules
+                .bands
+                .iter()
+                .find(|band| band.contains(d))
+                .map(|band| {
+                    self.monopole_and_quadrupole(position, center_of_mass, band, quadrupole)
+                })
+                .unwrap_or(Vec3::ZERO);
+        }
+
+        let mut sum = Vec3::ZERO;
+        let mut count = 0u32;
+        for band in rules.bands.iter().filter(|band| band.contains(d)) {
+            sum += self.monopole_and_quadrupole(position, center_of_mass, band, quadrupole);
+            count += 1;
+        }
+
+        match rules.mode {
+            CombineMode::Average if count > 0 => sum / count as f32,
+            _ => sum,
+        }
+    }
+
+    /// Monopole force, plus the quadrupole correction when `band.distance_exp
+    /// == -2` (see `quadrupole`), of this node's aggregate evaluated as if
+    /// its center of mass were at `center_of_mass`. `quadrupole` is `self`'s
+    /// tensor, passed in rather than recomputed per call.
+    fn monopole_and_quadrupole(
+        &self,
+        position: Vec3,
+        center_of_mass: Vec3,
+        band: &ForceBand,
+        quadrupole: Mat3,
+    ) -> Vec3 {
+        let mut f = force(
+            position,
+            center_of_mass,
+            self.mass,
+            band.force,
+            band.distance_exp,
+        );
+
+        // The quadrupole correction only follows from the field of a `1/r`
+        // potential, so it's only valid for distance_exp == -2; other
+        // exponents keep the plain monopole above.
+        let d = center_of_mass - position;
+        let dist = d.length();
+        if band.distance_exp == -2 && dist > f32::EPSILON {
+            let n = d / dist;
+            let qn = quadrupole * n;
+            let n_qn = n.dot(qn);
+            f += band.force * (5.0 * n * n_qn - 2.0 * qn) / (2.0 * dist.powi(5));
+        }
+
+        f
+    }
+
+    /// Boids-style traversal: accepted nodes within `rules.radius` contribute
+    /// cohesion, alignment, and separation terms instead of a gravity-like
+    /// force. Uses the same opening criterion as `force` to decide whether
+    /// to descend, and the same minimum-image displacement under a periodic
+    /// domain so neighbors across the seam are still felt.
+    fn flock(
+        &self,
+        id: TId,
+        position: Vec3,
+        velocity: Vec3,
+        rules: &FlockRules,
+        domain_size: f32,
     ) -> Vec3 {
         match &self.kind {
             NodeKind::Empty => Vec3::ZERO,
             NodeKind::Leaf(node_id) => {
                 if id != *node_id {
-                    force(
-                        position,
-                        self.center_of_mass,
+                    flock_contribution(
+                        velocity,
+                        minimum_image(self.center_of_mass() - position, domain_size),
+                        self.average_velocity(),
                         self.mass,
-                        force_constant,
-                        distance_exp,
+                        rules,
                     )
                 } else {
                     Vec3::ZERO
                 }
             }
             NodeKind::Node(nodes) => {
-                let d = (self.center_of_mass - position).length();
-                if self.size / d < theta {
-                    force(
-                        position,
-                        self.center_of_mass,
+                let center_of_mass = self.center_of_mass();
+                let d = minimum_image(center_of_mass - position, domain_size).length();
+                if self.size / d < rules.theta {
+                    flock_contribution(
+                        velocity,
+                        minimum_image(center_of_mass - position, domain_size),
+                        self.average_velocity(),
                         self.mass,
-                        force_constant,
-                        distance_exp,
+                        rules,
                     )
                 } else {
                     let mut force = Vec3::ZERO;
                     for node in nodes.iter() {
-                        force += node.force(id, position, force_constant, distance_exp, theta);
+                        force += node.flock(id, position, velocity, rules, domain_size);
                     }
                     force
                 }
@@ -158,6 +551,23 @@ enum NodeKind<TId: Id> {
     Node(Box<[Node<TId>; 8]>),
 }
 
+fn outer(r: Vec3) -> Mat3 {
+    Mat3::from_cols(r * r.x, r * r.y, r * r.z)
+}
+
+/// Shifts `delta` by whole multiples of `domain_size` on each axis so every
+/// component lands in `[-domain_size/2, domain_size/2]` — the displacement
+/// to the nearest periodic image. A component exactly on the boundary keeps
+/// its magnitude and flips sign.
+fn minimum_image(delta: Vec3, domain_size: f32) -> Vec3 {
+    delta - domain_size * (delta / domain_size).round()
+}
+
+/// Wraps `position` into `[-domain_size/2, domain_size/2]` on each axis.
+pub fn wrap_periodic(position: Vec3, domain_size: f32) -> Vec3 {
+    minimum_image(position, domain_size)
+}
+
 fn branch_index(position: Vec3, midpoint: Vec3) -> usize {
     let offset = position - midpoint;
     let onoff = (offset.signum() + 1.0) / 2.0;
@@ -180,3 +590,136 @@ fn force(
         * d.normalize_or_zero()
         * ((d.length() + f32::EPSILON).powi(distance_exp))
 }
+
+/// `d` is the (possibly minimum-image) displacement from the querying body
+/// to the other body/node.
+fn flock_contribution(
+    velocity: Vec3,
+    d: Vec3,
+    other_velocity: Vec3,
+    other_mass: f32,
+    rules: &FlockRules,
+) -> Vec3 {
+    if other_mass <= 0.0 {
+        return Vec3::ZERO;
+    }
+
+    let dist = d.length();
+    if dist > rules.radius || dist <= f32::EPSILON {
+        return Vec3::ZERO;
+    }
+
+    let cohesion_force = rules.cohesion * other_mass * d;
+    let alignment_force = rules.alignment * other_mass * (other_velocity - velocity);
+    let separation_force = -rules.separation * other_mass * d / dist.powi(3);
+
+    cohesion_force + alignment_force + separation_force
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remove_last_body_collapses_node_to_empty() {
+        let mut tree = BarnesHutTree::<u32>::new(10.0);
+        tree.insert(1, vec3(1.0, 1.0, 1.0), Vec3::ZERO, 2.0);
+
+        tree.remove(1, vec3(1.0, 1.0, 1.0));
+
+        assert!(matches!(tree.root.kind, NodeKind::Empty));
+        assert_eq!(tree.root.count, 0);
+        assert_eq!(tree.root.mass, 0.0);
+    }
+
+    #[test]
+    fn remove_one_of_two_collapses_node_to_leaf() {
+        let mut tree = BarnesHutTree::<u32>::new(10.0);
+        tree.insert(1, vec3(1.0, 1.0, 1.0), Vec3::ZERO, 2.0);
+        tree.insert(2, vec3(-1.0, -1.0, -1.0), Vec3::ZERO, 3.0);
+        assert!(matches!(tree.root.kind, NodeKind::Node(_)));
+
+        tree.remove(2, vec3(-1.0, -1.0, -1.0));
+
+        assert!(matches!(tree.root.kind, NodeKind::Leaf(1)));
+        assert_eq!(tree.root.count, 1);
+        assert_eq!(tree.root.mass, 2.0);
+    }
+
+    #[test]
+    fn remove_then_insert_leaves_tree_consistent() {
+        let mut tree = BarnesHutTree::<u32>::new(10.0);
+        tree.insert(1, vec3(1.0, 1.0, 1.0), Vec3::ZERO, 2.0);
+        tree.insert(2, vec3(-1.0, -1.0, -1.0), Vec3::ZERO, 3.0);
+
+        tree.remove(1, vec3(1.0, 1.0, 1.0));
+        tree.insert(3, vec3(1.0, -1.0, 1.0), Vec3::ZERO, 4.0);
+
+        assert_eq!(tree.root.count, 2);
+        assert_eq!(tree.root.mass, 7.0);
+        assert_eq!(
+            tree.root.moment,
+            vec3(-1.0, -1.0, -1.0) * 3.0 + vec3(1.0, -1.0, 1.0) * 4.0
+        );
+    }
+
+    #[test]
+    fn update_in_same_branch_moves_moments_without_changing_branch() {
+        let mut tree = BarnesHutTree::<u32>::new(10.0);
+        tree.insert(1, vec3(1.0, 1.0, 1.0), Vec3::ZERO, 2.0);
+        tree.insert(2, vec3(1.5, 1.5, 1.5), vec3(1.0, 0.0, 0.0), 1.0);
+
+        tree.update(
+            2,
+            vec3(1.5, 1.5, 1.5),
+            vec3(1.4, 1.4, 1.4),
+            vec3(1.0, 0.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+            1.0,
+        );
+
+        assert_eq!(tree.root.count, 2);
+        assert_eq!(tree.root.mass, 3.0);
+        assert_eq!(tree.root.moment, vec3(1.0, 1.0, 1.0) * 2.0 + vec3(1.4, 1.4, 1.4));
+        assert_eq!(
+            tree.root.velocity_moment,
+            Vec3::ZERO * 2.0 + vec3(0.0, 1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn update_across_branches_moves_body_to_new_branch() {
+        let mut tree = BarnesHutTree::<u32>::new(10.0);
+        tree.insert(1, vec3(1.0, 1.0, 1.0), Vec3::ZERO, 2.0);
+        tree.insert(2, vec3(-1.0, -1.0, -1.0), Vec3::ZERO, 3.0);
+
+        tree.update(
+            2,
+            vec3(-1.0, -1.0, -1.0),
+            vec3(2.0, 2.0, 2.0),
+            Vec3::ZERO,
+            Vec3::ZERO,
+            3.0,
+        );
+
+        assert_eq!(tree.root.count, 2);
+        assert_eq!(tree.root.mass, 5.0);
+        assert_eq!(tree.root.moment, vec3(1.0, 1.0, 1.0) * 2.0 + vec3(2.0, 2.0, 2.0) * 3.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "update: id not found at old_position")]
+    fn update_panics_when_id_missing_from_expected_leaf() {
+        let mut tree = BarnesHutTree::<u32>::new(10.0);
+        tree.insert(1, vec3(1.0, 1.0, 1.0), Vec3::ZERO, 2.0);
+
+        tree.update(
+            99,
+            vec3(1.0, 1.0, 1.0),
+            vec3(1.2, 1.2, 1.2),
+            Vec3::ZERO,
+            Vec3::ZERO,
+            2.0,
+        );
+    }
+}
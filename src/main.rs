@@ -1,10 +1,14 @@
 mod barnes_hut;
 mod choose_color;
 
-use std::vec;
+use std::{
+    collections::{HashMap, HashSet},
+    vec,
+};
 
-use barnes_hut::BarnesHutTree;
+use barnes_hut::{BarnesHutTree, CombineMode, FlockRules, ForceBand, ForceRules};
 use bevy::{
+    math::vec3,
     prelude::{shape::UVSphere, *},
     window::WindowMode,
 };
@@ -24,6 +28,22 @@ const NUM_PARTICLES: usize = 2000;
 const PARTICLE_SIZE: f32 = 0.01;
 const PARTICLE_FORCE_MAX: f32 = 1e-5;
 const BH_THETA: f32 = 1.0;
+// Side length of the periodic simulation domain particles wrap around; keeps
+// particle-life patterns stable instead of drifting apart and removes edge
+// artifacts from an unbounded space.
+const DOMAIN_SIZE: f32 = 2.0;
+
+// Relative speed below which a collision fuses the two bodies instead of
+// just bouncing.
+const MERGE_SPEED_THRESHOLD: f32 = 0.02;
+// Reduced-mass kinetic energy above which a collision shatters the larger
+// body instead of just bouncing.
+const FRAGMENT_ENERGY_THRESHOLD: f32 = 5e-5;
+const MIN_FRAGMENTS: usize = 2;
+const MAX_FRAGMENTS: usize = 4;
+const FRAGMENT_SPREAD_SPEED: f32 = 0.05;
+// Per-kind body cap so runaway fragmentation can't explode the entity count.
+const MAX_PARTICLES_PER_KIND: usize = NUM_PARTICLES / NUM_KINDS;
 
 fn main() {
     let mut app = App::new();
@@ -49,7 +69,8 @@ fn main() {
     .insert_resource(ParticleSystem::rand(&mut thread_rng(), NUM_KINDS))
     .init_resource::<ParticleTrees>()
     .add_startup_system(setup_world)
-    .add_system(barnes_hut)
+    .add_system(merge_and_fragment_particles)
+    .add_system(barnes_hut.after(merge_and_fragment_particles))
     .add_system(update_forces.after(barnes_hut));
 
     #[cfg(feature = "editor")]
@@ -91,6 +112,9 @@ fn setup_world(
         })
         .collect();
 
+    commands.insert_resource(SphereMesh(sphere_mesh.clone()));
+    commands.insert_resource(KindMaterials(color_materials.clone()));
+
     let mut rng = thread_rng();
     for _ in 0..NUM_PARTICLES {
         let kind_i = rng.gen_range(0..kinds.len());
@@ -111,83 +135,329 @@ fn setup_world(
             .insert(RigidBody::Dynamic)
             .insert(Collider::ball(PARTICLE_SIZE))
             .insert(Restitution::coefficient(0.0))
+            .insert(ActiveEvents::COLLISION_EVENTS)
             .insert(ExternalForce::default())
-            .insert(ReadMassProperties::default());
+            .insert(ReadMassProperties::default())
+            .insert(Velocity::default());
     }
 }
 
 fn barnes_hut(
     particle_system: Res<ParticleSystem>,
     mut particle_trees: ResMut<ParticleTrees>,
-    particles: Query<(Entity, &ParticleKindHandle, &Transform, &ReadMassProperties)>,
+    removed: RemovedComponents<ParticleKindHandle>,
+    particles: Query<(
+        Entity,
+        &ParticleKindHandle,
+        &Transform,
+        &Velocity,
+        &ReadMassProperties,
+    )>,
 ) {
-    let bounds = particles.iter().fold(
-        vec![
-            (f32::INFINITY * Vec3::ONE, -f32::INFINITY * Vec3::ONE);
-            particle_system.kinds().count()
-        ],
-        |mut bounds, (_, pk, t, _)| {
-            let (min, max) = &mut bounds[pk.0];
-
-            *min = min.min(t.translation);
-            *max = max.max(t.translation);
-
-            bounds
-        },
-    );
+    // The trees themselves persist across frames (see `ParticleTrees`); they're
+    // only built from scratch the first time this runs, when there's nothing
+    // to mutate incrementally yet.
+    if particle_trees.trees.is_empty() {
+        particle_trees.trees = particle_system
+            .kinds()
+            .map(|_| BarnesHutTree::new(DOMAIN_SIZE))
+            .collect();
+    }
 
-    *particle_trees = ParticleTrees(
-        bounds
-            .into_iter()
-            .map(|(min, max)| BarnesHutTree::new(min, max))
-            .collect(),
-    );
+    for entity in removed.iter() {
+        if let Some((pk, position, _)) = particle_trees.positions.remove(&entity) {
+            particle_trees.tree_mut(pk).remove(entity, position);
+        }
+    }
 
-    for (e, pk, t, m) in particles.iter() {
+    for (e, pk, t, v, m) in particles.iter() {
+        match particle_trees.positions.get(&e).copied() {
+            Some((prev_pk, prev_position, prev_velocity)) if prev_pk.0 == pk.0 => {
+                if prev_position != t.translation || prev_velocity != v.linvel {
+                    particle_trees.tree_mut(*pk).update(
+                        e,
+                        prev_position,
+                        t.translation,
+                        prev_velocity,
+                        v.linvel,
+                        m.0.mass,
+                    );
+                }
+            }
+            Some((prev_pk, prev_position, _)) => {
+                particle_trees.tree_mut(prev_pk).remove(e, prev_position);
+                particle_trees
+                    .tree_mut(*pk)
+                    .insert(e, t.translation, v.linvel, m.0.mass);
+            }
+            None => {
+                particle_trees
+                    .tree_mut(*pk)
+                    .insert(e, t.translation, v.linvel, m.0.mass);
+            }
+        }
         particle_trees
-            .tree_mut(*pk)
-            .insert(e, t.translation, m.0.mass)
+            .positions
+            .insert(e, (*pk, t.translation, v.linvel));
     }
 }
 
 fn update_forces(
     particle_system: Res<ParticleSystem>,
     particle_trees: Res<ParticleTrees>,
-    mut particles: Query<(Entity, &ParticleKindHandle, &Transform, &mut ExternalForce)>,
+    mut particles: Query<(
+        Entity,
+        &ParticleKindHandle,
+        &mut Transform,
+        &Velocity,
+        &mut ExternalForce,
+    )>,
 ) {
-    for (e1, pk1, t1, mut f) in particles.iter_mut() {
+    for (e1, pk1, mut t1, v1, mut f) in particles.iter_mut() {
+        // Pull drifters back into the periodic domain before the forces below
+        // read their position (`barnes_hut` has already seated this frame's
+        // unwrapped position in the tree); the Barnes-Hut forces use the
+        // minimum image regardless, so this is purely to keep positions (and
+        // the next frame's tree) from drifting ever further from the origin.
+        t1.translation = barnes_hut::wrap_periodic(t1.translation, DOMAIN_SIZE);
+
         let mut force = Vec3::ZERO;
 
         for pk in particle_system.kinds() {
             let _span = info_span!("barnes_hut_force", name = "barnes_hut_force").entered();
             let rule = particle_system.rule(*pk1, pk);
-            force += particle_trees.tree(pk).force(
-                e1,
-                t1.translation,
-                rule.force,
-                rule.distance_exp,
-                BH_THETA,
-            );
+            let tree = particle_trees.tree(pk);
+
+            force += tree.force(e1, t1.translation, &rule.force_rules, BH_THETA);
+            force += tree.flock(e1, t1.translation, v1.linvel, &rule.flock_rules);
         }
 
         *f = ExternalForce { force, ..default() }
     }
 }
 
+/// Fuses particles on slow collisions and shatters them on fast ones,
+/// mirroring the classic fusion/fragmentation/crater gravity-sim roadmap.
+/// Mass and linear momentum are conserved across both outcomes.
+fn merge_and_fragment_particles(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    particle_system: Res<ParticleSystem>,
+    sphere_mesh: Res<SphereMesh>,
+    kind_materials: Res<KindMaterials>,
+    bodies: Query<(
+        &ParticleKindHandle,
+        &Transform,
+        &Velocity,
+        &ReadMassProperties,
+    )>,
+) {
+    let mut counts = vec![0usize; particle_system.num_kinds];
+    for (pk, _, _, _) in bodies.iter() {
+        counts[pk.0] += 1;
+    }
+
+    let mut rng = thread_rng();
+    let mut despawned = HashSet::new();
+
+    for event in collision_events.iter() {
+        let CollisionEvent::Started(e1, e2, _) = event else {
+            continue;
+        };
+        if despawned.contains(e1) || despawned.contains(e2) {
+            continue;
+        }
+
+        let (Ok((pk1, t1, v1, m1)), Ok((pk2, t2, v2, m2))) = (bodies.get(*e1), bodies.get(*e2))
+        else {
+            continue;
+        };
+
+        let mass1 = m1.0.mass;
+        let mass2 = m2.0.mass;
+        if mass1 <= 0.0 || mass2 <= 0.0 {
+            continue;
+        }
+        let total_mass = mass1 + mass2;
+
+        let relative_speed = (v1.linvel - v2.linvel).length();
+
+        if relative_speed < MERGE_SPEED_THRESHOLD {
+            let fused_kind = if mass1 >= mass2 { *pk1 } else { *pk2 };
+
+            let com_position = (t1.translation * mass1 + t2.translation * mass2) / total_mass;
+            let com_velocity = (v1.linvel * mass1 + v2.linvel * mass2) / total_mass;
+
+            commands.entity(*e1).despawn();
+            commands.entity(*e2).despawn();
+            despawned.insert(*e1);
+            despawned.insert(*e2);
+            counts[pk1.0] -= 1;
+            counts[pk2.0] -= 1;
+            counts[fused_kind.0] += 1;
+
+            spawn_particle(
+                &mut commands,
+                &sphere_mesh,
+                &kind_materials,
+                fused_kind,
+                com_position,
+                com_velocity,
+                total_mass,
+            );
+            continue;
+        }
+
+        // Kinetic energy of the relative motion, the part that actually goes
+        // into breaking the body apart rather than moving its center of mass.
+        let impact_energy = 0.5 * (mass1 * mass2 / total_mass) * relative_speed * relative_speed;
+        if impact_energy > FRAGMENT_ENERGY_THRESHOLD {
+            let (big_entity, big_kind, big_position, big_velocity, big_mass) = if mass1 >= mass2 {
+                (*e1, *pk1, t1.translation, v1.linvel, mass1)
+            } else {
+                (*e2, *pk2, t2.translation, v2.linvel, mass2)
+            };
+
+            let fragment_count = rng.gen_range(MIN_FRAGMENTS..=MAX_FRAGMENTS);
+            if counts[big_kind.0] - 1 + fragment_count > MAX_PARTICLES_PER_KIND {
+                continue;
+            }
+
+            commands.entity(big_entity).despawn();
+            despawned.insert(big_entity);
+            counts[big_kind.0] -= 1;
+
+            let fragment_masses = random_mass_split(&mut rng, big_mass, fragment_count);
+            let spreads = zero_mean_spread_vectors(&mut rng, &fragment_masses);
+            for (fragment_mass, spread) in fragment_masses.into_iter().zip(spreads) {
+                let velocity = big_velocity + spread * FRAGMENT_SPREAD_SPEED;
+                spawn_particle(
+                    &mut commands,
+                    &sphere_mesh,
+                    &kind_materials,
+                    big_kind,
+                    big_position,
+                    velocity,
+                    fragment_mass,
+                );
+                counts[big_kind.0] += 1;
+            }
+        }
+    }
+}
+
+/// Random positive weights summing to 1, scaled by `total_mass`, so the
+/// fragments' masses sum back to exactly what the body had before breaking.
+fn random_mass_split<R: Rng>(rng: &mut R, total_mass: f32, count: usize) -> Vec<f32> {
+    let weights: Vec<f32> = (0..count).map(|_| rng.gen_range(0.2..1.0)).collect();
+    let total_weight: f32 = weights.iter().sum();
+    weights
+        .into_iter()
+        .map(|w| total_mass * w / total_weight)
+        .collect()
+}
+
+/// Random spread vectors whose *mass-weighted* sum is exactly zero, so
+/// adding `spread_i * FRAGMENT_SPREAD_SPEED` to the shared base velocity
+/// doesn't bias the fragments' net momentum (`Σ mass_i * spread_i == 0`,
+/// not just `Σ spread_i == 0`, since fragments generally have unequal
+/// masses). Subtracting the mass-weighted mean instead of the plain mean
+/// moves the directions off the unit sphere, so their lengths (and thus how
+/// fast each fragment actually spreads) vary rather than being uniform.
+fn zero_mean_spread_vectors<R: Rng>(rng: &mut R, masses: &[f32]) -> Vec<Vec3> {
+    let directions: Vec<Vec3> = masses
+        .iter()
+        .map(|_| {
+            vec3(
+                rng.gen::<f32>() - 0.5,
+                rng.gen::<f32>() - 0.5,
+                rng.gen::<f32>() - 0.5,
+            )
+            .normalize_or_zero()
+        })
+        .collect();
+
+    let total_mass: f32 = masses.iter().sum();
+    let weighted_mean = directions
+        .iter()
+        .zip(masses)
+        .map(|(direction, mass)| *direction * *mass)
+        .sum::<Vec3>()
+        / total_mass;
+
+    directions
+        .into_iter()
+        .map(|direction| direction - weighted_mean)
+        .collect()
+}
+
+fn spawn_particle(
+    commands: &mut Commands,
+    sphere_mesh: &SphereMesh,
+    kind_materials: &KindMaterials,
+    kind: ParticleKindHandle,
+    position: Vec3,
+    velocity: Vec3,
+    mass: f32,
+) {
+    let radius = radius_for_mass(mass);
+
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: sphere_mesh.0.clone(),
+            material: kind_materials.0[kind.0].clone(),
+            transform: Transform::from_translation(position)
+                .with_scale(Vec3::splat(radius / PARTICLE_SIZE)),
+            ..default()
+        })
+        .insert(kind)
+        .insert(RigidBody::Dynamic)
+        .insert(Collider::ball(radius))
+        .insert(Restitution::coefficient(0.0))
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(ExternalForce::default())
+        .insert(ReadMassProperties::default())
+        .insert(Velocity {
+            linvel: velocity,
+            ..default()
+        });
+}
+
+// Radius of a uniform-density sphere (matching the default collider density
+// of 1.0) that would have the given mass.
+fn radius_for_mass(mass: f32) -> f32 {
+    (3.0 * mass.max(f32::EPSILON) / (4.0 * std::f32::consts::PI)).cbrt()
+}
+
+/// Mesh shared by every spawned particle; collider radius (and hence visual
+/// size) is conveyed through `Transform::scale` instead of separate meshes.
+struct SphereMesh(Handle<Mesh>);
+
+/// Material handle per kind, so fused/fragmented particles keep their kind's
+/// color without re-running the color picker.
+struct KindMaterials(Vec<Handle<StandardMaterial>>);
+
+/// Holds one persistent `BarnesHutTree` per kind, alive across frames, plus
+/// the last-known kind and position of every tracked entity so `barnes_hut`
+/// can re-seat movers with `update`/`remove` instead of rebuilding from
+/// scratch every frame.
 #[derive(Debug, Default)]
-struct ParticleTrees(Vec<BarnesHutTree<Entity>>);
+struct ParticleTrees {
+    trees: Vec<BarnesHutTree<Entity>>,
+    positions: HashMap<Entity, (ParticleKindHandle, Vec3, Vec3)>,
+}
 
 impl ParticleTrees {
     fn tree(&self, pk: ParticleKindHandle) -> &BarnesHutTree<Entity> {
-        &self.0[pk.0]
+        &self.trees[pk.0]
     }
 
     fn tree_mut(&mut self, pk: ParticleKindHandle) -> &mut BarnesHutTree<Entity> {
-        &mut self.0[pk.0]
+        &mut self.trees[pk.0]
     }
 }
 
-#[derive(Clone, Copy, Component)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
 struct ParticleKindHandle(usize);
 
 #[derive(Debug)]
@@ -198,18 +468,31 @@ struct ParticleSystem {
 
 #[derive(Debug)]
 struct ParticleRule {
-    force: f32,
-    distance_exp: i32,
+    force_rules: ForceRules,
+    // Boids-style weights for the kind pair, applied alongside the
+    // gravity-like force above.
+    flock_rules: FlockRules,
 }
 
+// Number of distance bands generated per kind pair; enough to express a
+// short-range repulsion followed by a longer-range attraction (or vice
+// versa) without a combinatorial explosion of rules.
+const NUM_FORCE_BANDS: usize = 2;
+
 impl ParticleSystem {
     pub fn rand<R: Rng>(rng: &mut R, num_kinds: usize) -> Self {
         Self {
             num_kinds,
             rules: (0..(num_kinds * num_kinds))
                 .map(|_| ParticleRule {
-                    force: 2.0 * PARTICLE_FORCE_MAX * (rng.gen::<f32>() - 0.5),
-                    distance_exp: rng.gen_range(-2..=1),
+                    force_rules: rand_force_rules(rng),
+                    flock_rules: FlockRules {
+                        radius: rng.gen_range(0.05..=0.2),
+                        cohesion: PARTICLE_FORCE_MAX * rng.gen::<f32>(),
+                        alignment: PARTICLE_FORCE_MAX * rng.gen::<f32>(),
+                        separation: PARTICLE_FORCE_MAX * rng.gen::<f32>(),
+                        theta: BH_THETA,
+                    },
                 })
                 .collect(),
         }
@@ -227,3 +510,69 @@ impl ParticleSystem {
         pk1.0 * self.num_kinds + pk2.0
     }
 }
+
+/// Random `NUM_FORCE_BANDS` overlapping distance bands covering `[0, ∞)`,
+/// each with its own force and exponent, combined per a randomly chosen
+/// `CombineMode` so emergent structure is richer than a single force term.
+/// Bands after the first start at half of the previous band's cutoff
+/// rather than exactly at it, so neighboring bands overlap (e.g. a
+/// short-range repulsion still active where a long-range attraction
+/// begins) and `Sum`/`Average` actually differ from `Priority` at
+/// runtime instead of every distance falling in exactly one band.
+fn rand_force_rules<R: Rng>(rng: &mut R) -> ForceRules {
+    let mut cutoffs: Vec<f32> = (0..NUM_FORCE_BANDS - 1)
+        .map(|_| rng.gen_range(0.02..=0.2))
+        .collect();
+    cutoffs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let mut bands = Vec::with_capacity(NUM_FORCE_BANDS);
+    let mut prev_max = 0.0;
+    for max_dist in cutoffs.into_iter().chain([f32::INFINITY]) {
+        let min_dist = if prev_max == 0.0 { 0.0 } else { prev_max / 2.0 };
+        bands.push(ForceBand {
+            force: 2.0 * PARTICLE_FORCE_MAX * (rng.gen::<f32>() - 0.5),
+            distance_exp: rng.gen_range(-2..=1),
+            min_dist,
+            max_dist,
+        });
+        prev_max = max_dist;
+    }
+
+    let mode = match rng.gen_range(0..3) {
+        0 => CombineMode::Sum,
+        1 => CombineMode::Average,
+        _ => CombineMode::Priority,
+    };
+
+    ForceRules { bands, mode }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_mass_split_sums_to_total_mass() {
+        let mut rng = thread_rng();
+        let masses = random_mass_split(&mut rng, 10.0, 5);
+
+        assert_eq!(masses.len(), 5);
+        assert!(masses.iter().all(|&m| m > 0.0));
+        assert!((masses.iter().sum::<f32>() - 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn zero_mean_spread_vectors_conserve_mass_weighted_momentum() {
+        let mut rng = thread_rng();
+        let masses = random_mass_split(&mut rng, 10.0, 6);
+
+        let spreads = zero_mean_spread_vectors(&mut rng, &masses);
+
+        let weighted_sum: Vec3 = masses
+            .iter()
+            .zip(&spreads)
+            .map(|(mass, spread)| *mass * *spread)
+            .sum();
+        assert!(weighted_sum.length() < 1e-3);
+    }
+}